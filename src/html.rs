@@ -42,7 +42,9 @@ struct StringWrap<'w>(&'w mut String);
 
 struct StrWriteMutRef<'w, W>(&'w mut W);
 
-// TODO: expose this?
+/// Abstracts over the sinks [`HtmlWriter`] can render into, so the same
+/// event-handling code works whether the destination is a `String` (no
+/// UTF-8 validation needed on write) or an [`io::Write`](std::io::Write).
 pub trait StrWrite {
     fn write_str(&mut self, s: &str) -> io::Result<()>;
 
@@ -85,7 +87,353 @@ impl<W> StrWrite for StrWriteMutRef<'_, W>
     }
 }
 
-struct HtmlWriter<'a, I, W> {
+/// Configuration for [`write_html_with_options`], gating the rendering
+/// features layered on top of the plain [`write_html`] output. The `'cb`
+/// lifetime bounds any callbacks registered on it (e.g.
+/// [`code_block_highlighter`](HtmlOptions::code_block_highlighter)).
+pub struct HtmlOptions<'cb> {
+    heading_anchors: bool,
+    toc: bool,
+    code_block_highlighter: Option<Box<dyn FnMut(&str, &str) -> String + 'cb>>,
+    external_link_base_host: Option<String>,
+    link_rewriter: Option<Box<dyn FnMut(&str) -> Option<CowStr<'static>> + 'cb>>,
+    image_attrs: Option<Box<dyn FnMut(&str, &str) -> ImageAttrs + 'cb>>,
+}
+
+impl<'cb> Default for HtmlOptions<'cb> {
+    fn default() -> Self {
+        HtmlOptions {
+            heading_anchors: false,
+            toc: false,
+            code_block_highlighter: None,
+            external_link_base_host: None,
+            link_rewriter: None,
+            image_attrs: None,
+        }
+    }
+}
+
+impl<'cb> HtmlOptions<'cb> {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Give every heading a unique `id` attribute, slugified from its text,
+    /// mirroring rustdoc's `IdMap`. Repeated slugs are disambiguated as
+    /// `foo`, `foo-1`, `foo-2`, ...
+    pub fn heading_anchors(mut self, enabled: bool) -> Self {
+        self.heading_anchors = enabled;
+        self
+    }
+
+    /// Build a [`Toc`] alongside the HTML, retrievable with
+    /// [`write_html_with_toc`]. Implies `heading_anchors`, since the TOC
+    /// links to the headings it lists.
+    pub fn toc(mut self, enabled: bool) -> Self {
+        self.toc = enabled;
+        self
+    }
+
+    /// Registers a callback invoked once per code block (fenced or
+    /// indented; an indented block's language token is empty) with its
+    /// language token and the full accumulated code text, returning
+    /// pre-rendered HTML that is written verbatim in place of the default
+    /// escaped body. Lets integrators wire in a highlighter such as
+    /// syntect without post-processing the rendered HTML.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use pulldown_cmark::{html, Parser};
+    /// use pulldown_cmark::html::HtmlOptions;
+    ///
+    /// let markdown_str = "```rust\nfn main() {}\n```";
+    /// let parser = Parser::new(markdown_str);
+    ///
+    /// let mut html_buf = Vec::new();
+    /// html::write_html_with_options(
+    ///     &mut html_buf,
+    ///     parser,
+    ///     HtmlOptions::new().code_block_highlighter(|lang, code| {
+    ///         format!("<mark data-lang=\"{}\">{}</mark>", lang, code.trim())
+    ///     }),
+    /// ).unwrap();
+    ///
+    /// assert_eq!(String::from_utf8(html_buf).unwrap(), "<pre><code class=\"language-rust\">\
+    /// <mark data-lang=\"rust\">fn main() {}</mark></code></pre>\n");
+    /// ```
+    pub fn code_block_highlighter<F>(mut self, highlighter: F) -> Self
+    where
+        F: FnMut(&str, &str) -> String + 'cb,
+    {
+        self.code_block_highlighter = Some(Box::new(highlighter));
+        self
+    }
+
+    /// Marks any link whose destination host differs from `host` as
+    /// external, appending `rel="noopener noreferrer" target="_blank"` to
+    /// it so it opens safely in a new tab.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use pulldown_cmark::{html, Parser};
+    /// use pulldown_cmark::html::HtmlOptions;
+    ///
+    /// let markdown_str = "[Away](https://other.example/page)";
+    /// let parser = Parser::new(markdown_str);
+    ///
+    /// let mut html_buf = Vec::new();
+    /// html::write_html_with_options(
+    ///     &mut html_buf,
+    ///     parser,
+    ///     HtmlOptions::new().external_link_base_host("example.com"),
+    /// ).unwrap();
+    ///
+    /// assert_eq!(String::from_utf8(html_buf).unwrap(), "<p><a href=\"https://other.example/page\" \
+    /// rel=\"noopener noreferrer\" target=\"_blank\">Away</a></p>\n");
+    /// ```
+    pub fn external_link_base_host<S: Into<String>>(mut self, host: S) -> Self {
+        self.external_link_base_host = Some(host.into());
+        self
+    }
+
+    /// Registers a callback that can rewrite or normalize a link's
+    /// destination (e.g. resolving relative doc links) before
+    /// `escape_href` runs. Returning `None` leaves the destination as-is.
+    /// Runs before the [`external_link_base_host`](Self::external_link_base_host)
+    /// check, so a rewritten destination is what gets classified as
+    /// external or not. Not invoked for email autolinks such as
+    /// `<john@example.org>`, whose destinations are bare email addresses
+    /// rendered as `mailto:` links; an ordinary `[name](dest)` link is
+    /// passed to it whatever its destination.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use pulldown_cmark::{html, Parser};
+    /// use pulldown_cmark::html::HtmlOptions;
+    ///
+    /// let markdown_str = "[Docs](relative/page)";
+    /// let parser = Parser::new(markdown_str);
+    ///
+    /// let mut html_buf = Vec::new();
+    /// html::write_html_with_options(
+    ///     &mut html_buf,
+    ///     parser,
+    ///     HtmlOptions::new()
+    ///         .external_link_base_host("example.com")
+    ///         .link_rewriter(|dest| {
+    ///             if dest.starts_with("http") {
+    ///                 None
+    ///             } else {
+    ///                 Some(format!("https://example.com/{}", dest).into())
+    ///             }
+    ///         }),
+    /// ).unwrap();
+    ///
+    /// assert_eq!(String::from_utf8(html_buf).unwrap(), "<p><a href=\"https://example.com/relative/page\">Docs</a></p>\n");
+    /// ```
+    pub fn link_rewriter<F>(mut self, rewriter: F) -> Self
+    where
+        F: FnMut(&str) -> Option<CowStr<'static>> + 'cb,
+    {
+        self.link_rewriter = Some(Box::new(rewriter));
+        self
+    }
+
+    /// Registers a callback invoked once per image with its `src` and
+    /// (already-extracted) alt text, returning extra attributes to add to
+    /// the `<img>` tag, e.g. responsive classes or lazy-loading hints.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use pulldown_cmark::{html, Parser};
+    /// use pulldown_cmark::html::{HtmlOptions, ImageAttrs};
+    ///
+    /// let markdown_str = "![alt text](src.png)";
+    /// let parser = Parser::new(markdown_str);
+    ///
+    /// let mut html_buf = Vec::new();
+    /// html::write_html_with_options(
+    ///     &mut html_buf,
+    ///     parser,
+    ///     HtmlOptions::new().image_attrs(|_src, _alt| ImageAttrs {
+    ///         lazy: true,
+    ///         async_decoding: true,
+    ///         class: Some("rounded".to_string()),
+    ///         sizes: None,
+    ///     }),
+    /// ).unwrap();
+    ///
+    /// assert_eq!(String::from_utf8(html_buf).unwrap(), "<p><img src=\"src.png\" alt=\"alt text\" \
+    /// loading=\"lazy\" decoding=\"async\" class=\"rounded\" /></p>\n");
+    /// ```
+    pub fn image_attrs<F>(mut self, callback: F) -> Self
+    where
+        F: FnMut(&str, &str) -> ImageAttrs + 'cb,
+    {
+        self.image_attrs = Some(Box::new(callback));
+        self
+    }
+}
+
+/// Extra `<img>` attributes an [`HtmlOptions::image_attrs`] callback can
+/// ask for. All fields default to leaving the attribute off.
+#[derive(Clone, Default)]
+pub struct ImageAttrs {
+    pub lazy: bool,
+    pub async_decoding: bool,
+    pub class: Option<String>,
+    pub sizes: Option<String>,
+}
+
+/// Extracts the host component from an absolute or protocol-relative URL
+/// (e.g. `"https://example.com/path"` or `"//example.com/path"` ->
+/// `Some("example.com")`), stripping any userinfo or port. Returns `None`
+/// for relative or non-URL destinations.
+fn url_host(url: &str) -> Option<&str> {
+    let rest = if let Some(stripped) = url.strip_prefix("//") {
+        stripped
+    } else {
+        let sep = url.find("://")?;
+        let scheme = &url[..sep];
+        let is_scheme_char = |c: char| c.is_ascii_alphanumeric() || c == '+' || c == '-' || c == '.';
+        if scheme.is_empty() || !scheme.chars().all(is_scheme_char) {
+            return None;
+        }
+        &url[sep + 3..]
+    };
+    let authority = rest
+        .split(&['/', '?', '#'][..])
+        .next()
+        .unwrap_or("");
+    let host_port = authority.rsplit('@').next().unwrap_or(authority);
+    let host = host_port.split(':').next().unwrap_or(host_port);
+    if host.is_empty() {
+        None
+    } else {
+        Some(host)
+    }
+}
+
+/// A single entry in a [`Toc`]: a heading's level, rendered text, anchor
+/// id, and the nested headings found beneath it.
+pub struct TocEntry {
+    pub level: i32,
+    pub name: String,
+    pub id: String,
+    pub children: Toc,
+}
+
+/// A nested outline of a document's headings, as produced by
+/// [`write_html_with_toc`]. Mirrors rustdoc's `TocBuilder` output.
+#[derive(Default)]
+pub struct Toc(pub Vec<TocEntry>);
+
+impl Toc {
+    pub fn new() -> Toc {
+        Toc(Vec::new())
+    }
+
+    /// Renders this outline as nested `<ul>` navigation markup, e.g. for a
+    /// doc sidebar.
+    pub fn to_html(&self) -> String {
+        let mut buf = String::new();
+        self.write_html(StringWrap(&mut buf)).unwrap();
+        buf
+    }
+
+    fn write_html<W: StrWrite>(&self, mut writer: W) -> io::Result<()> {
+        if self.0.is_empty() {
+            return Ok(());
+        }
+        writer.write_str("<ul>")?;
+        for entry in &self.0 {
+            writer.write_str("<li><a href=\"#")?;
+            escape_href(StrWriteMutRef(&mut writer), &entry.id)?;
+            writer.write_str("\">")?;
+            escape_html(StrWriteMutRef(&mut writer), &entry.name)?;
+            writer.write_str("</a>")?;
+            entry.children.write_html(StrWriteMutRef(&mut writer))?;
+            writer.write_str("</li>")?;
+        }
+        writer.write_str("</ul>")
+    }
+}
+
+/// Incrementally folds a flat sequence of heading levels into a [`Toc`]
+/// tree. Maintains a stack of the currently open headings: a new heading
+/// pops every entry whose level is greater-or-equal to its own (closing
+/// siblings and descendants alike) before being pushed itself, so levels
+/// can skip (e.g. an h1 directly followed by an h3) without breaking the
+/// nesting.
+#[derive(Default)]
+struct TocBuilder {
+    top_level: Toc,
+    chain: Vec<TocEntry>,
+}
+
+impl TocBuilder {
+    fn new() -> TocBuilder {
+        TocBuilder::default()
+    }
+
+    fn push(&mut self, level: i32, name: String, id: String) {
+        self.close_levels_geq(level);
+        self.chain.push(TocEntry { level, name, id, children: Toc::new() });
+    }
+
+    fn close_levels_geq(&mut self, level: i32) {
+        while let Some(top) = self.chain.last() {
+            if top.level < level {
+                break;
+            }
+            let entry = self.chain.pop().unwrap();
+            match self.chain.last_mut() {
+                Some(parent) => parent.children.0.push(entry),
+                None => self.top_level.0.push(entry),
+            }
+        }
+    }
+
+    fn into_toc(mut self) -> Toc {
+        self.close_levels_geq(0);
+        self.top_level
+    }
+}
+
+/// Lowercases `text`, drops everything but alphanumerics and whitespace,
+/// then collapses runs of whitespace into single hyphens.
+fn slugify(text: &str) -> String {
+    text.chars()
+        .flat_map(char::to_lowercase)
+        .filter(|c| c.is_alphanumeric() || c.is_whitespace())
+        .collect::<String>()
+        .split_whitespace()
+        .collect::<Vec<_>>()
+        .join("-")
+}
+
+/// Builds the `fnref-` id for the `occurrence`-th (1-based) citation of a
+/// footnote named `name`, so repeated citations of the same footnote get
+/// distinct ids instead of colliding: the 1st citation is `fnref-name`,
+/// the 2nd `fnref-name-1`, the 3rd `fnref-name-2`, etc. — the same scheme
+/// `unique_id` uses for repeated heading slugs.
+fn fnref_id(name: &str, occurrence: usize) -> String {
+    if occurrence <= 1 {
+        format!("fnref-{}", name)
+    } else {
+        format!("fnref-{}-{}", name, occurrence - 1)
+    }
+}
+
+/// The built-in event-to-HTML renderer backing [`write_html`] and friends.
+/// Public so other crates can reuse its escaping, table-state tracking,
+/// and footnote-numbering logic rather than reimplementing the event
+/// loop; see [`EventRenderer`] for the override mechanism.
+pub struct HtmlWriter<'a, I, W> {
     /// Iterator supplying events.
     iter: I,
 
@@ -99,6 +447,30 @@ struct HtmlWriter<'a, I, W> {
     table_alignments: Vec<Alignment>,
     table_cell_index: usize,
     numbers: HashMap<CowStr<'a>, usize>,
+
+    opts: HtmlOptions<'a>,
+
+    /// Slug -> number of times it's been used, so repeats become `slug-1`,
+    /// `slug-2`, etc.
+    id_map: HashMap<String, usize>,
+
+    /// Present when `opts.toc` is set; accumulates the headings seen into
+    /// a [`Toc`] alongside the HTML.
+    toc_builder: Option<TocBuilder>,
+
+    /// Set for the duration of a `Tag::FootnoteDefinition`, so its closing
+    /// paragraph can be detected and given a back-reference link.
+    in_footnote: bool,
+
+    /// Set when a footnote's final paragraph's `</p>` has been withheld so
+    /// the back-reference link can be inserted before it.
+    close_para: bool,
+
+    /// Number of `FootnoteReference`s seen so far for each name, so a
+    /// footnote cited more than once gets one distinct `fnref-` id per
+    /// citation (disambiguated like `unique_id`'s slugs) instead of
+    /// colliding on a single repeated id.
+    footnote_refs: HashMap<CowStr<'a>, usize>,
 }
 
 impl<'a, I, W> HtmlWriter<'a, I, W>
@@ -106,6 +478,27 @@ where
     I: Iterator<Item = Event<'a>>,
     W: StrWrite,
 {
+    /// Builds a renderer over `iter`, writing to `writer` under the given
+    /// `options`. Call [`run`](HtmlWriter::run) (or drive it through
+    /// [`EventRenderer`]) to render.
+    pub fn new(iter: I, writer: W, options: HtmlOptions<'a>) -> Self {
+        HtmlWriter {
+            iter,
+            writer,
+            end_newline: true,
+            table_state: TableState::Head,
+            table_alignments: vec![],
+            table_cell_index: 0,
+            numbers: HashMap::new(),
+            opts: options,
+            id_map: HashMap::new(),
+            toc_builder: None,
+            in_footnote: false,
+            close_para: false,
+            footnote_refs: HashMap::new(),
+        }
+    }
+
     /// Writes a new line.
     fn write_newline(&mut self) -> io::Result<()> {
         self.end_newline = true;
@@ -136,56 +529,124 @@ where
         }
     }
 
-    pub fn run(mut self) -> io::Result<()> {
+    /// Flushes a footnote paragraph's `</p>` that was withheld so a
+    /// back-reference link could be inserted before it. Called from every
+    /// place that can follow that paragraph other than the footnote's own
+    /// end tag, which flushes it alongside the back-link instead.
+    fn flush_pending_footnote_para(&mut self) -> io::Result<()> {
+        if self.close_para {
+            self.write("</p>", true)?;
+            self.close_para = false;
+        }
+        Ok(())
+    }
+
+    pub fn run(&mut self) -> io::Result<()> {
         while let Some(event) = self.iter.next() {
-            match event {
-                Start(tag) => {
-                    self.start_tag(tag)?;
-                }
-                End(tag) => {
-                    self.end_tag(tag)?;
-                }
-                Text(text) => {
-                    escape_html(StrWriteMutRef(&mut self.writer), &text)?;
-                    self.end_newline = text.ends_with('\n');
-                }
-                Code(text) => {
-                    self.write("<code>", false)?;
-                    escape_html(StrWriteMutRef(&mut self.writer), &text)?;
-                    self.write("</code>", false)?;
-                    self.end_newline = false;
-                }
-                Html(html) | InlineHtml(html) => {
-                    self.write(&html, false)?;
-                }
-                SoftBreak => {
-                    self.write_newline()?;
-                }
-                HardBreak => {
-                    self.write("<br />", true)?;
-                }
-                FootnoteReference(name) => {
-                    let len = self.numbers.len() + 1;
-                    self.write("<sup class=\"footnote-reference\"><a href=\"#", false)?;
-                    escape_html(StrWriteMutRef(&mut self.writer), &name)?;
-                    self.write("\">", false)?;
-                    let number = *self.numbers.entry(name).or_insert(len);
-                    write!(&mut self.writer, "{}", number)?;
-                    self.write("</a></sup>", false)?;
-                }
-                TaskListMarker(true) => {
-                    self.write("<input disabled=\"\" type=\"checkbox\" checked=\"\"/>", true)?;
-                }
-                TaskListMarker(false) => {
-                    self.write("<input disabled=\"\" type=\"checkbox\"/>", true)?;
-                }
+            self.dispatch_event(event)?;
+        }
+        Ok(())
+    }
+
+    /// Renders a single event. Factored out of `run` so that buffered
+    /// events (e.g. the contents of a heading) can be replayed through the
+    /// same logic used for the top-level iterator.
+    ///
+    /// This is the single chokepoint that flushes a withheld footnote
+    /// paragraph close before *any* other event, rather than relying on
+    /// each tag handler to remember to do it — the only exception is the
+    /// footnote definition's own end tag, which flushes it alongside the
+    /// back-reference link below instead.
+    fn dispatch_event(&mut self, event: Event<'a>) -> io::Result<()> {
+        if let End(Tag::FootnoteDefinition(_)) = event {
+            // left for `end_tag` to flush alongside the back-link
+        } else {
+            self.flush_pending_footnote_para()?;
+        }
+        match event {
+            Start(tag) => {
+                self.start_tag(tag)?;
+            }
+            End(tag) => {
+                self.end_tag(tag)?;
+            }
+            Text(text) => {
+                self.text(text)?;
+            }
+            Code(text) => {
+                self.write("<code>", false)?;
+                escape_html(StrWriteMutRef(&mut self.writer), &text)?;
+                self.write("</code>", false)?;
+                self.end_newline = false;
+            }
+            Html(html) | InlineHtml(html) => {
+                self.write(&html, false)?;
+            }
+            SoftBreak => {
+                self.write_newline()?;
+            }
+            HardBreak => {
+                self.write("<br />", true)?;
+            }
+            FootnoteReference(name) => {
+                let count = self.footnote_refs.entry(name.clone()).or_insert(0);
+                *count += 1;
+                let ref_id = fnref_id(&name, *count);
+                let len = self.numbers.len() + 1;
+                self.write("<sup class=\"footnote-reference\" id=\"", false)?;
+                escape_html(StrWriteMutRef(&mut self.writer), &ref_id)?;
+                self.write("\"><a href=\"#", false)?;
+                escape_html(StrWriteMutRef(&mut self.writer), &name)?;
+                self.write("\">", false)?;
+                let number = *self.numbers.entry(name).or_insert(len);
+                write!(&mut self.writer, "{}", number)?;
+                self.write("</a></sup>", false)?;
+            }
+            TaskListMarker(true) => {
+                self.write("<input disabled=\"\" type=\"checkbox\" checked=\"\"/>", true)?;
+            }
+            TaskListMarker(false) => {
+                self.write("<input disabled=\"\" type=\"checkbox\"/>", true)?;
             }
         }
         Ok(())
     }
 
-    /// Writes the start of an HTML tag.
-    fn start_tag(&mut self, tag: Tag<'a>) -> io::Result<()> {
+    /// Slugifies `text` and disambiguates it against ids already handed
+    /// out, so repeated headings get distinct anchors. Every id this
+    /// returns is itself recorded (not just the base slug), so a heading
+    /// that happens to collide with an already-disambiguated form (e.g. a
+    /// literal "Foo 1" after two "Foo" headings) keeps looking until it
+    /// finds one nobody has used yet, the way rustdoc's `IdMap` does.
+    fn unique_id(&mut self, text: &str) -> String {
+        let slug = slugify(text);
+        if !self.id_map.contains_key(&slug) {
+            self.id_map.insert(slug.clone(), 0);
+            return slug;
+        }
+        loop {
+            let count = self.id_map.get_mut(&slug).unwrap();
+            *count += 1;
+            let candidate = format!("{}-{}", slug, count);
+            if !self.id_map.contains_key(&candidate) {
+                self.id_map.insert(candidate.clone(), 0);
+                return candidate;
+            }
+        }
+    }
+
+    /// Escapes and writes a run of text. Exposed so [`EventRenderer`]
+    /// implementations can fall back to the default behavior.
+    pub fn text(&mut self, text: CowStr<'a>) -> io::Result<()> {
+        escape_html(StrWriteMutRef(&mut self.writer), &text)?;
+        self.end_newline = text.ends_with('\n');
+        Ok(())
+    }
+
+    /// Writes the start of an HTML tag. Exposed so [`EventRenderer`]
+    /// implementations can fall back to the default behavior for tags
+    /// they don't override.
+    pub fn start_tag(&mut self, tag: Tag<'a>) -> io::Result<()> {
         match tag {
             Tag::Paragraph => {
                 self.fresh_line()?;
@@ -198,6 +659,9 @@ where
             Tag::Header(level) => {
                 self.fresh_line()?;
                 self.end_newline = false;
+                if self.opts.heading_anchors || self.opts.toc {
+                    return self.start_header_with_anchor(level);
+                }
                 write!(&mut self.writer, "<h{}>", level)
             }
             Tag::Table(alignments) => {
@@ -244,12 +708,16 @@ where
                 self.fresh_line()?;
                 let lang = info.split(' ').next().unwrap();
                 if lang.is_empty() {
-                    self.write("<pre><code>", false)
+                    self.write("<pre><code>", false)?;
                 } else {
                     self.write("<pre><code class=\"language-", false)?;
                     escape_html(StrWriteMutRef(&mut self.writer), lang)?;
-                    self.write("\">", false)
+                    self.write("\">", false)?;
                 }
+                if self.opts.code_block_highlighter.is_some() {
+                    return self.write_highlighted_code_block(lang);
+                }
+                Ok(())
             }
             Tag::List(Some(1)) => {
                 self.fresh_line()?;
@@ -273,6 +741,9 @@ where
             Tag::Strong => self.write("<strong>", false),
             Tag::Strikethrough => self.write("<del>", false),
             Tag::Link(LinkType::Email, dest, title) => {
+                // Not passed through `link_rewriter`: that hook is for
+                // doc-link style destinations (see its doc comment), and
+                // an email address isn't a URL a rewriter would expect.
                 self.write("<a href=\"mailto:", false)?;
                 escape_href(StrWriteMutRef(&mut self.writer), &dest)?;
                 if !title.is_empty() {
@@ -282,27 +753,56 @@ where
                 self.write("\">", false)
             }
             Tag::Link(_link_type, dest, title) => {
+                let dest = self.rewrite_link(dest);
                 self.write("<a href=\"", false)?;
                 escape_href(StrWriteMutRef(&mut self.writer), &dest)?;
+                self.write("\"", false)?;
+                if self.is_external_link(&dest) {
+                    self.write(" rel=\"noopener noreferrer\" target=\"_blank\"", false)?;
+                }
                 if !title.is_empty() {
-                    self.write("\" title=\"", false)?;
+                    self.write(" title=\"", false)?;
                     escape_html(StrWriteMutRef(&mut self.writer), &title)?;
+                    self.write("\"", false)?;
                 }
-                self.write("\">", false)
+                self.write(">", false)
             }
             Tag::Image(_link_type, dest, title) => {
+                let alt = self.raw_text()?;
                 self.write("<img src=\"", false)?;
                 escape_href(StrWriteMutRef(&mut self.writer), &dest)?;
                 self.write("\" alt=\"", false)?;
-                self.raw_text()?;
+                escape_html(StrWriteMutRef(&mut self.writer), &alt)?;
+                self.write("\"", false)?;
                 if !title.is_empty() {
-                    self.write("\" title=\"", false)?;
+                    self.write(" title=\"", false)?;
                     escape_html(StrWriteMutRef(&mut self.writer), &title)?;
+                    self.write("\"", false)?;
                 }
-                self.write("\" />", false)
+                if let Some(callback) = self.opts.image_attrs.as_mut() {
+                    let attrs = callback(&dest, &alt);
+                    if attrs.lazy {
+                        self.write(" loading=\"lazy\"", false)?;
+                    }
+                    if attrs.async_decoding {
+                        self.write(" decoding=\"async\"", false)?;
+                    }
+                    if let Some(class) = &attrs.class {
+                        self.write(" class=\"", false)?;
+                        escape_html(StrWriteMutRef(&mut self.writer), class)?;
+                        self.write("\"", false)?;
+                    }
+                    if let Some(sizes) = &attrs.sizes {
+                        self.write(" sizes=\"", false)?;
+                        escape_html(StrWriteMutRef(&mut self.writer), sizes)?;
+                        self.write("\"", false)?;
+                    }
+                }
+                self.write(" />", false)
             }
             Tag::FootnoteDefinition(name) => {
                 self.fresh_line()?;
+                self.in_footnote = true;
                 let len = self.numbers.len() + 1;
                 self.write("<div class=\"footnote-definition\" id=\"", false)?;
                 escape_html(StrWriteMutRef(&mut self.writer), &*name)?;
@@ -315,10 +815,17 @@ where
         }
     }
 
-    fn end_tag(&mut self, tag: Tag) -> io::Result<()> {
+    /// Writes the end of an HTML tag. Exposed so [`EventRenderer`]
+    /// implementations can fall back to the default behavior for tags
+    /// they don't override.
+    pub fn end_tag(&mut self, tag: Tag) -> io::Result<()> {
         match tag {
             Tag::Paragraph => {
-                self.write("</p>", true)?;
+                if self.in_footnote {
+                    self.close_para = true;
+                } else {
+                    self.write("</p>", true)?;
+                }
             }
             Tag::Rule => (),
             Tag::Header(level) => {
@@ -374,7 +881,24 @@ where
                 self.write("</a>", false)?;
             }
             Tag::Image(_, _, _) => (), // shouldn't happen, handled in start
-            Tag::FootnoteDefinition(_) => {
+            Tag::FootnoteDefinition(name) => {
+                // The back-reference is owed regardless of what the
+                // definition's last block was; only a withheld paragraph's
+                // `</p>` needs to come after it instead of before.
+                let after_para = self.close_para;
+                self.close_para = false;
+                // One backref per citation, so a footnote cited more than
+                // once links back to each place that cited it rather than
+                // colliding on a single repeated id.
+                let citations = *self.footnote_refs.get(&name).unwrap_or(&0);
+                for occurrence in 1..=citations {
+                    let ref_id = fnref_id(&name, occurrence);
+                    self.write("<a href=\"#", false)?;
+                    escape_html(StrWriteMutRef(&mut self.writer), &ref_id)?;
+                    self.write("\" class=\"footnote-backref\">\u{21a9}</a>", false)?;
+                }
+                self.write(if after_para { "</p>" } else { "" }, true)?;
+                self.in_footnote = false;
                 self.write("</div>", true)?;
             }
             Tag::HtmlBlock => {}
@@ -382,8 +906,12 @@ where
         Ok(())
     }
 
-    // run raw text, consuming end tag
-    fn raw_text(&mut self) -> io::Result<()> {
+    /// Collects the plain text of a nested run of events (consuming up to
+    /// and including its end tag), e.g. an image's alt text. Unlike
+    /// `push_plain_text`, this tracks nesting depth since the run may
+    /// contain arbitrarily nested inline tags (emphasis, links, ...).
+    fn raw_text(&mut self) -> io::Result<String> {
+        let mut text = String::new();
         let mut nest = 0;
         while let Some(event) = self.iter.next() {
             match event {
@@ -395,26 +923,204 @@ where
                     nest -= 1;
                 }
                 Html(_) => (),
-                InlineHtml(text) | Code(text) | Text(text) => {
-                    escape_html(StrWriteMutRef(&mut self.writer), &text)?;
-                    self.end_newline = text.ends_with('\n');
-                }
-                SoftBreak | HardBreak => {
-                    self.write(" ", false)?;
-                }
+                InlineHtml(t) | Code(t) | Text(t) => text.push_str(&t),
+                SoftBreak | HardBreak => text.push(' '),
                 FootnoteReference(name) => {
                     let len = self.numbers.len() + 1;
                     let number = *self.numbers.entry(name).or_insert(len);
-                    write!(&mut self.writer, "[{}]", number)?;
+                    text.push_str(&format!("[{}]", number));
                 }
-                TaskListMarker(true) => self.write("[x]", false)?,
-                TaskListMarker(false) => self.write("[ ]", false)?,
+                TaskListMarker(true) => text.push_str("[x]"),
+                TaskListMarker(false) => text.push_str("[ ]"),
+            }
+        }
+        Ok(text)
+    }
+
+    /// Buffers the events between `Start(Header)` and the matching
+    /// `End(Header)`, since the slug for the opening tag's `id` can only be
+    /// computed once the heading's text is known. The buffered events are
+    /// then replayed so the heading's contents render normally.
+    fn start_header_with_anchor(&mut self, level: i32) -> io::Result<()> {
+        let mut buffer = Vec::new();
+        let mut plain_text = String::new();
+        while let Some(event) = self.iter.next() {
+            if let End(Tag::Header(_)) = event {
+                break;
+            }
+            push_plain_text(&event, &mut plain_text);
+            buffer.push(event);
+        }
+        let id = self.unique_id(&plain_text);
+        if let Some(builder) = self.toc_builder.as_mut() {
+            builder.push(level, plain_text.clone(), id.clone());
+        }
+        self.write("<h", false)?;
+        write!(&mut self.writer, "{}", level)?;
+        self.write(" id=\"", false)?;
+        escape_html(StrWriteMutRef(&mut self.writer), &id)?;
+        self.write("\">", false)?;
+        for event in buffer {
+            self.dispatch_event(event)?;
+        }
+        self.end_tag(Tag::Header(level))
+    }
+
+    /// Buffers the `Text` events inside a fenced code block into one
+    /// string, hands it to the registered highlighter callback, and writes
+    /// the result verbatim rather than escaping each chunk as it arrives.
+    fn write_highlighted_code_block(&mut self, lang: &str) -> io::Result<()> {
+        let mut code = String::new();
+        loop {
+            match self.iter.next() {
+                Some(Text(text)) => code.push_str(&text),
+                Some(End(Tag::CodeBlock(_))) | None => break,
+                Some(event) => self.dispatch_event(event)?,
+            }
+        }
+        let rendered = (self.opts.code_block_highlighter.as_mut().unwrap())(lang, &code);
+        self.write(&rendered, false)?;
+        self.write("</code></pre>", true)
+    }
+
+    /// Runs the registered link rewriter, if any, over a link destination.
+    fn rewrite_link(&mut self, dest: CowStr<'a>) -> CowStr<'a> {
+        match self.opts.link_rewriter.as_mut() {
+            Some(rewriter) => match rewriter(&dest) {
+                Some(rewritten) => rewritten,
+                None => dest,
+            },
+            None => dest,
+        }
+    }
+
+    /// Whether `dest`'s host differs from the configured base host, i.e.
+    /// whether the link should be treated as external.
+    fn is_external_link(&self, dest: &str) -> bool {
+        match (&self.opts.external_link_base_host, url_host(dest)) {
+            (Some(base), Some(host)) => !host.eq_ignore_ascii_case(base),
+            _ => false,
+        }
+    }
+}
+
+/// Lets a custom renderer reuse [`HtmlWriter`]'s escaping, table-state
+/// tracking, and footnote-numbering logic while selectively overriding how
+/// individual tags render. Implement this on a type wrapping an
+/// `HtmlWriter` and override only the methods you need to customize; their
+/// default bodies forward to the built-in HTML behavior via
+/// [`base`](EventRenderer::base).
+///
+/// # Examples
+///
+/// ```
+/// use pulldown_cmark::{CowStr, Event, Parser};
+/// use pulldown_cmark::html::{EventRenderer, HtmlOptions, HtmlWriter, StrWrite};
+/// use std::io;
+///
+/// struct Shout<'a, I, W> {
+///     inner: HtmlWriter<'a, I, W>,
+/// }
+///
+/// impl<'a, I, W> EventRenderer<'a, I, W> for Shout<'a, I, W>
+/// where
+///     I: Iterator<Item = Event<'a>>,
+///     W: StrWrite,
+/// {
+///     fn base(&mut self) -> &mut HtmlWriter<'a, I, W> {
+///         &mut self.inner
+///     }
+///
+///     // Reuse the built-in escaping, just uppercase the text first.
+///     fn text(&mut self, text: CowStr<'a>) -> io::Result<()> {
+///         self.base().text(text.to_uppercase().into())
+///     }
+/// }
+///
+/// let markdown_str = "hello world";
+/// let parser = Parser::new(markdown_str);
+///
+/// let mut buf: Vec<u8> = Vec::new();
+/// let mut renderer = Shout {
+///     inner: HtmlWriter::new(parser, &mut buf, HtmlOptions::new()),
+/// };
+/// renderer.run().unwrap();
+///
+/// assert_eq!(String::from_utf8(buf).unwrap(), "<p>HELLO WORLD</p>\n");
+/// ```
+pub trait EventRenderer<'a, I, W>
+where
+    I: Iterator<Item = Event<'a>>,
+    W: StrWrite,
+{
+    /// The underlying built-in renderer that default methods fall back to.
+    fn base(&mut self) -> &mut HtmlWriter<'a, I, W>;
+
+    fn start_tag(&mut self, tag: Tag<'a>) -> io::Result<()> {
+        self.base().start_tag(tag)
+    }
+
+    fn end_tag(&mut self, tag: Tag<'a>) -> io::Result<()> {
+        self.base().end_tag(tag)
+    }
+
+    fn text(&mut self, text: CowStr<'a>) -> io::Result<()> {
+        self.base().text(text)
+    }
+
+    /// Drives the event loop, routing `Start`/`End`/`Text` events through
+    /// the (possibly overridden) methods above and everything else through
+    /// the built-in default handling.
+    ///
+    /// Flushes a withheld footnote paragraph close at the same chokepoint
+    /// `HtmlWriter::dispatch_event` uses, before routing to any handler, so
+    /// overriding `start_tag`/`end_tag`/`text` here can't skip it.
+    ///
+    /// When the built-in `start_tag` renders a heading with an `id`, or a
+    /// code block with a registered highlighter, it consumes that element's
+    /// contents and end tag itself, so those events go through
+    /// `HtmlWriter`'s own handling and do not reach overridden
+    /// `end_tag`/`text` methods.
+    fn run(&mut self) -> io::Result<()> {
+        while let Some(event) = self.base().iter.next() {
+            if let End(Tag::FootnoteDefinition(_)) = event {
+                // left for `end_tag` to flush alongside the back-link
+            } else {
+                self.base().flush_pending_footnote_para()?;
+            }
+            match event {
+                Start(tag) => self.start_tag(tag)?,
+                End(tag) => self.end_tag(tag)?,
+                Text(text) => self.text(text)?,
+                other => self.base().dispatch_event(other)?,
             }
         }
         Ok(())
     }
 }
 
+impl<'a, I, W> EventRenderer<'a, I, W> for HtmlWriter<'a, I, W>
+where
+    I: Iterator<Item = Event<'a>>,
+    W: StrWrite,
+{
+    fn base(&mut self) -> &mut HtmlWriter<'a, I, W> {
+        self
+    }
+}
+
+/// Appends the plain-text contribution of a single event to `out`, used to
+/// build the text a heading's slug is derived from.
+fn push_plain_text(event: &Event, out: &mut String) {
+    match event {
+        Text(text) | Code(text) | InlineHtml(text) => out.push_str(text),
+        SoftBreak | HardBreak => out.push(' '),
+        TaskListMarker(true) => out.push_str("[x]"),
+        TaskListMarker(false) => out.push_str("[ ]"),
+        _ => (),
+    }
+}
+
 /// Iterate over an `Iterator` of `Event`s, generate HTML for each `Event`, and
 /// push it to a `String`.
 ///
@@ -442,6 +1148,53 @@ where
 /// </ul>
 /// "#);
 /// ```
+///
+/// Footnote definitions get a back-reference link to where they were
+/// cited, placed at the end of the definition's last block:
+///
+/// ```
+/// use pulldown_cmark::{html, Options, Parser};
+///
+/// let markdown_str = "Hello[^a] world.\n\n[^a]: The note.\n";
+/// let parser = Parser::new_ext(markdown_str, Options::ENABLE_FOOTNOTES);
+///
+/// let mut html_buf = String::new();
+/// html::push_html(&mut html_buf, parser);
+///
+/// assert_eq!(html_buf, "<p>Hello\
+/// <sup class=\"footnote-reference\" id=\"fnref-a\"><a href=\"#a\">1</a></sup> \
+/// world.</p>\n\
+/// <div class=\"footnote-definition\" id=\"a\">\
+/// <sup class=\"footnote-definition-label\">1</sup>\n\
+/// <p>The note.\
+/// <a href=\"#fnref-a\" class=\"footnote-backref\">\u{21a9}</a></p>\n\
+/// </div>\n");
+/// ```
+///
+/// A footnote cited more than once gets one distinct `fnref-` id per
+/// citation, and the definition links back to each of them in turn:
+///
+/// ```
+/// use pulldown_cmark::{html, Options, Parser};
+///
+/// let markdown_str = "One[^a] two[^a].\n\n[^a]: Note.\n";
+/// let parser = Parser::new_ext(markdown_str, Options::ENABLE_FOOTNOTES);
+///
+/// let mut html_buf = String::new();
+/// html::push_html(&mut html_buf, parser);
+///
+/// assert_eq!(html_buf, "<p>One\
+/// <sup class=\"footnote-reference\" id=\"fnref-a\"><a href=\"#a\">1</a></sup> \
+/// two\
+/// <sup class=\"footnote-reference\" id=\"fnref-a-1\"><a href=\"#a\">1</a></sup>\
+/// .</p>\n\
+/// <div class=\"footnote-definition\" id=\"a\">\
+/// <sup class=\"footnote-definition-label\">1</sup>\n\
+/// <p>Note.\
+/// <a href=\"#fnref-a\" class=\"footnote-backref\">\u{21a9}</a>\
+/// <a href=\"#fnref-a-1\" class=\"footnote-backref\">\u{21a9}</a></p>\n\
+/// </div>\n");
+/// ```
 pub fn push_html<'a, I>(s: &mut String, iter: I)
 where
     I: Iterator<Item = Event<'a>>,
@@ -449,6 +1202,81 @@ where
     write_html(StringWrap(s), iter).unwrap();
 }
 
+/// Like [`write_html`], but renders using the features gated behind
+/// [`HtmlOptions`] (e.g. heading anchors) instead of the plain default
+/// output.
+///
+/// # Examples
+///
+/// ```
+/// use pulldown_cmark::{html, Parser};
+/// use pulldown_cmark::html::HtmlOptions;
+///
+/// let markdown_str = "# Hello World\n\n# Hello World";
+/// let parser = Parser::new(markdown_str);
+///
+/// let mut html_buf = Vec::new();
+/// html::write_html_with_options(
+///     &mut html_buf,
+///     parser,
+///     HtmlOptions::new().heading_anchors(true),
+/// ).unwrap();
+///
+/// assert_eq!(String::from_utf8(html_buf).unwrap(), "<h1 id=\"hello-world\">Hello World</h1>\n\
+/// <h1 id=\"hello-world-1\">Hello World</h1>\n");
+/// ```
+pub fn write_html_with_options<'a, I, W>(writer: W, iter: I, options: HtmlOptions<'a>) -> io::Result<()>
+where
+    I: Iterator<Item = Event<'a>>,
+    W: StrWrite,
+{
+    HtmlWriter::new(iter, writer, options).run()
+}
+
+/// Like [`write_html_with_options`], but also builds a [`Toc`] of the
+/// document's headings as it renders (implicitly turning on
+/// `heading_anchors`, since TOC entries link to their heading).
+///
+/// # Examples
+///
+/// ```
+/// use pulldown_cmark::{html, Parser};
+/// use pulldown_cmark::html::HtmlOptions;
+///
+/// let markdown_str = "# Title\n\n## Section\n\n### Subsection\n\n## Another Section";
+///
+/// let mut html_buf = String::new();
+/// let toc = html::write_html_with_toc(
+///     &mut html_buf,
+///     Parser::new(markdown_str),
+///     HtmlOptions::new(),
+/// ).unwrap();
+///
+/// assert_eq!(toc.0.len(), 1);
+/// assert_eq!(toc.0[0].children.0.len(), 2);
+/// ```
+pub fn write_html_with_toc<'a, I>(s: &mut String, iter: I, options: HtmlOptions<'a>) -> io::Result<Toc>
+where
+    I: Iterator<Item = Event<'a>>,
+{
+    let mut writer = HtmlWriter::new(iter, StringWrap(s), options.toc(true));
+    writer.toc_builder = Some(TocBuilder::new());
+    writer.run()?;
+    Ok(writer.toc_builder.take().unwrap().into_toc())
+}
+
+/// Convenience wrapper around [`write_html_with_toc`] that owns the output
+/// buffer, for callers who want the rendered HTML and its outline without
+/// parsing the document twice.
+pub fn html_with_toc<'a, I>(iter: I) -> (String, Toc)
+where
+    I: Iterator<Item = Event<'a>>,
+{
+    let mut html_buf = String::new();
+    let toc = write_html_with_toc(&mut html_buf, iter, HtmlOptions::new()).unwrap();
+    (html_buf, toc)
+}
+
 /// Iterate over an `Iterator` of `Event`s, generate HTML for each `Event`, and
 /// write it out to a writable stream.
 ///
@@ -487,14 +1315,5 @@ where
     I: Iterator<Item = Event<'a>>,
     W: StrWrite,
 {
-    let writer = HtmlWriter {
-        iter,
-        writer,
-        end_newline: true,
-        table_state: TableState::Head,
-        table_alignments: vec![],
-        table_cell_index: 0,
-        numbers: HashMap::new(),
-    };
-    writer.run()
+    write_html_with_options(writer, iter, HtmlOptions::default())
 }